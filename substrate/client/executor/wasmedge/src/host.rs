@@ -1,4 +1,10 @@
-use crate::util;
+// Lazy-paging for guest sandbox memories (apepkuss/substrate-wasmedge#chunk0-4) was attempted and
+// reverted rather than shipped: the signal-handler approach copied from the Gear executor only
+// mprotects the supervisor's own linear memory, which is already zero-copy to reach from this
+// crate, so there was no buffer copy left to avoid; the actual full-buffer copies the request
+// wanted removed are in `memory_get`/`memory_set`'s trip through the opaque `sandbox::Store`/guest
+// `Memory`, which this crate has no access to bypass. Treat chunk0-4 as deferred/won't-do rather
+// than done - it would need changes inside `sc_executor_common::sandbox` this crate can't make.
 use codec::{Decode, Encode};
 use log::trace;
 use sc_allocator::{AllocationStats, FreeingBumpHeapAllocator};
@@ -19,6 +25,30 @@ struct SandboxStore(Option<Box<sandbox::Store<Arc<FuncRef>>>>);
 // those within one thread so this should be safe.
 unsafe impl Send for SandboxStore {}
 
+/// An opaque snapshot of a [`HostState`]'s sandbox store, produced by [`HostState::snapshot`] and
+/// consumed by [`HostState::new_with_snapshot`] to carry instantiated guest modules across
+/// consecutive runtime calls instead of re-instantiating them each time.
+///
+/// Every instance this snapshot carries was registered with the dispatch thunk that
+/// [`instance_new`](Sandbox::instance_new) pulled out of *that* runtime call's supervisor table
+/// (see [`SandboxContext`]'s `dispatch_thunk`). A `wasmedge_sdk::FuncRef` does not keep its owning
+/// instance alive, so once the runtime call that produced this snapshot ends and its `Executor`
+/// and supervisor instance are torn down, every dispatch thunk this snapshot carries is dangling:
+/// calling into a restored instance invokes the supervisor through a thunk that points at
+/// memory/table slots the new call's `Executor` knows nothing about. See the `# Safety` section on
+/// [`HostState::snapshot`] and [`HostState::new_with_snapshot`].
+pub struct SandboxStoreSnapshot {
+	token: u64,
+	store: Box<sandbox::Store<Arc<FuncRef>>>,
+}
+
+impl SandboxStoreSnapshot {
+	/// The caller-provided token this snapshot was taken under.
+	pub fn token(&self) -> u64 {
+		self.token
+	}
+}
+
 /// The state required to construct a InstanceWrapper context. The context only lasts for one host
 /// call, whereas the state is maintained for the duration of a Wasm runtime call, which may make
 /// many different host calls that must share state.
@@ -26,18 +56,117 @@ pub struct HostState {
 	sandbox_store: SandboxStore,
 	allocator: Box<FreeingBumpHeapAllocator>,
 	panic_message: Option<String>,
+	/// The `Executor` used to dispatch supervisor calls back into the guest sandbox.
+	///
+	/// Created once per runtime call and reused across every `dispatch_thunk.call(...)` a guest
+	/// makes, rather than being allocated fresh on each individual host-function invocation.
+	executor: Executor,
+	/// An upper bound, in bytes, on how much heap `allocate_memory` will hand out. `None` means
+	/// no ceiling is enforced beyond whatever the linear memory can physically grow to.
+	max_heap_size: Option<u32>,
 }
 
 impl HostState {
-	/// Constructs a new `HostState`.
-	pub fn new(allocator: FreeingBumpHeapAllocator) -> Self {
-		HostState {
-			sandbox_store: SandboxStore(Some(Box::new(sandbox::Store::new(
-				sandbox::SandboxBackend::TryWasmer,
-			)))),
+	/// Constructs a new `HostState`, using `sandbox_backend` to drive the guest sandbox.
+	///
+	/// Pass [`sandbox::SandboxBackend::Wasmi`] for the deterministic interpreter, or
+	/// [`sandbox::SandboxBackend::TryWasmer`] to use the faster wasmer-based backend when it is
+	/// available and wasmi otherwise. This only picks between backends at runtime; it does not
+	/// gate whether the wasmer path is *compiled in* at all - this crate has no `wasmer-sandbox`
+	/// cargo feature of its own, so whether `TryWasmer` resolves to wasmer or to wasmi depends
+	/// entirely on how `sc_executor_common` was built, and choosing `TryWasmer` here can't change
+	/// that.
+	///
+	/// `max_heap_size`, if set, caps how many bytes `allocate_memory` will ever hand out to the
+	/// runtime, as counted by the allocator's `bytes_allocated`; allocations that would cross it
+	/// fail with an error from `allocate_memory`.
+	pub fn new(
+		allocator: FreeingBumpHeapAllocator,
+		sandbox_backend: sandbox::SandboxBackend,
+		max_heap_size: Option<u32>,
+	) -> Result<Self> {
+		let executor = Executor::new(None, None).map_err(|e| {
+			WasmError::Other(format!("fail to create a WasmEdge Executor context: {}", e))
+		})?;
+
+		Ok(HostState {
+			sandbox_store: SandboxStore(Some(Box::new(sandbox::Store::new(sandbox_backend)))),
 			allocator: Box::new(allocator),
 			panic_message: None,
-		}
+			executor,
+			max_heap_size,
+		})
+	}
+
+	fn executor_mut(&mut self) -> &mut Executor {
+		&mut self.executor
+	}
+
+	/// Takes a snapshot of this `HostState`'s sandbox store - its registered memories, tables,
+	/// and every instantiated guest module it currently holds - tagged with `token`. This consumes
+	/// the `HostState`, since the store backs every guest module instantiated against it and there
+	/// is no per-module handle to split off; a caller that wants to reuse only some of the
+	/// instances it has instantiated should keep them in separate `HostState`s to begin with. Feed
+	/// the result into [`HostState::new_with_snapshot`] on the next runtime call to resume from it
+	/// instead of instantiating the guest modules all over again.
+	///
+	/// `token` is not interpreted here; it's a caller-chosen identifier (e.g. a hash of the guest
+	/// wasm blob) round-tripped through [`SandboxStoreSnapshot::token`] so callers can check that
+	/// a snapshot they're about to restore actually corresponds to the guest code they expect.
+	///
+	/// # Safety
+	///
+	/// Every instance in the returned snapshot is registered with a dispatch thunk borrowed from
+	/// this runtime call's supervisor table. That thunk does not keep the supervisor instance or
+	/// `Executor` it came from alive, so it dangles the moment this call ends. The caller must not
+	/// let any guest code reach an instance carried by this snapshot — directly or via
+	/// [`HostState::new_with_snapshot`] — until every dispatch thunk it holds has been rebound to
+	/// the supervisor instance of the call that will use it. This crate does not implement that
+	/// rebinding, so there is currently no sound way to call `invoke` against a restored instance;
+	/// treat the restored store as instantiate-cache metadata only (e.g. to skip re-parsing wasm)
+	/// until rebinding exists.
+	pub unsafe fn snapshot(self, token: u64) -> SandboxStoreSnapshot {
+		let store = self
+			.sandbox_store
+			.0
+			.expect("sandbox store is only empty when temporarily borrowed");
+
+		SandboxStoreSnapshot { token, store }
+	}
+
+	/// Constructs a new `HostState` that resumes a previously taken [`SandboxStoreSnapshot`]
+	/// instead of starting with an empty sandbox store, so guest modules instantiated in an
+	/// earlier runtime call can be reused without being re-parsed and re-instantiated.
+	///
+	/// This restores the store's instances, tables and registered memories as they were at
+	/// snapshot time; it does not by itself reset any given memory's *contents* back to its
+	/// post-instantiation state; use [`HostContext::reset_memory`] for that once the runtime call
+	/// has a `HostContext` in hand.
+	///
+	/// # Safety
+	///
+	/// See the `# Safety` section on [`HostState::snapshot`]: every instance this resumes carries a
+	/// dispatch thunk left over from whichever runtime call produced `snapshot`, dangling once that
+	/// call's supervisor instance and `Executor` were dropped. The caller must not invoke any
+	/// instance carried by `snapshot` until its dispatch thunks have been rebound to the
+	/// supervisor instance of the call that constructs this `HostState`; this crate does not do
+	/// that rebinding.
+	pub unsafe fn new_with_snapshot(
+		allocator: FreeingBumpHeapAllocator,
+		max_heap_size: Option<u32>,
+		snapshot: SandboxStoreSnapshot,
+	) -> Result<Self> {
+		let executor = Executor::new(None, None).map_err(|e| {
+			WasmError::Other(format!("fail to create a WasmEdge Executor context: {}", e))
+		})?;
+
+		Ok(HostState {
+			sandbox_store: SandboxStore(Some(snapshot.store)),
+			allocator: Box::new(allocator),
+			panic_message: None,
+			executor,
+			max_heap_size,
+		})
 	}
 
 	/// Takes the error message out of the host state, leaving a `None` in its place.
@@ -52,6 +181,22 @@ impl HostState {
 	pub fn allocator(&mut self) -> &mut FreeingBumpHeapAllocator {
 		self.allocator.as_mut()
 	}
+
+	/// Returns an error if allocating `additional` more bytes would cross `max_heap_size`.
+	fn check_heap_ceiling(&self, additional: WordSize) -> sp_wasm_interface::Result<()> {
+		let Some(max_heap_size) = self.max_heap_size else { return Ok(()) };
+
+		let currently_allocated = self.allocator.stats().bytes_allocated;
+		if currently_allocated.saturating_add(additional) > max_heap_size {
+			return Err(format!(
+				"allocation of {} bytes would exceed the configured heap ceiling of {} bytes \
+				 ({} bytes already allocated)",
+				additional, max_heap_size, currently_allocated
+			))
+		}
+
+		Ok(())
+	}
 }
 
 /// A `HostContext` implements `FunctionContext` for making host calls from a WasmEdge
@@ -83,6 +228,105 @@ impl<'a> HostContext<'a> {
 			.as_mut()
 			.expect("sandbox store is only empty when temporarily borrowed")
 	}
+
+	/// Captures the first `len` bytes of sandboxed memory `memory_id`, typically called right
+	/// after a guest module has been instantiated, so a later call to [`reset_memory`] can roll
+	/// back any mutations the guest made to those bytes once the instance is reused via
+	/// [`HostState::new_with_snapshot`].
+	pub fn snapshot_memory(
+		&self,
+		memory_id: MemoryId,
+		len: WordSize,
+	) -> sp_wasm_interface::Result<Vec<u8>> {
+		self.sandbox_store()
+			.memory(memory_id)
+			.map_err(|e| e.to_string())?
+			.read(Pointer::new(0), len as usize)
+			.map_err(|e| e.to_string())
+	}
+
+	/// Restores the start of sandboxed memory `memory_id` from a snapshot previously taken with
+	/// [`snapshot_memory`], undoing any writes the guest made to the snapshotted bytes since then.
+	pub fn reset_memory(
+		&mut self,
+		memory_id: MemoryId,
+		snapshot: &[u8],
+	) -> sp_wasm_interface::Result<()> {
+		self.sandbox_store_mut()
+			.memory(memory_id)
+			.map_err(|e| e.to_string())?
+			.write_from(Pointer::new(0), snapshot)
+			.map_err(|e| e.to_string())
+	}
+}
+
+/// Implements [`MemoryTransfer`] over a WasmEdge [`Memory`].
+///
+/// The memory's current length is recomputed on every access rather than cached, so growth that
+/// happens mid-call is observed, and every read/write is bounds-checked against that length
+/// instead of trusting a raw pointer + length reconstructed by the caller. This is the one place
+/// in the crate allowed to turn the guest's linear memory into a Rust slice.
+struct WasmEdgeMemoryWrapper<'a>(&'a Memory);
+
+impl<'a> WasmEdgeMemoryWrapper<'a> {
+	fn new(memory: &'a Memory) -> Self {
+		WasmEdgeMemoryWrapper(memory)
+	}
+
+	fn data_len(&self) -> usize {
+		(self.0.size() * 64 * 1024) as usize
+	}
+
+	fn data_ptr(&self) -> std::result::Result<*mut u8, String> {
+		self.0
+			.data_pointer_mut(0, 1)
+			.map_err(|e| format!("failed to get the data pointer of the Memory: {}", e))
+	}
+
+	fn checked_range(&self, offset: Pointer<u8>, len: usize) -> std::result::Result<std::ops::Range<usize>, String> {
+		let start = u32::from(offset) as usize;
+		let end = start.checked_add(len).ok_or_else(|| "memory access overflowed".to_string())?;
+		if end > self.data_len() {
+			return Err("memory access is out of bounds".to_string())
+		}
+		Ok(start..end)
+	}
+
+	/// Returns the whole linear memory as a mutable slice, recomputing its current length.
+	///
+	/// # Safety
+	/// The caller must ensure no other live reference into the same memory exists for the
+	/// duration of the returned borrow.
+	unsafe fn full_slice_mut(&self) -> &'a mut [u8] {
+		std::slice::from_raw_parts_mut(
+			self.data_ptr().expect("failed to returns the mut data pointer to the Memory."),
+			self.data_len(),
+		)
+	}
+}
+
+impl<'a> MemoryTransfer for WasmEdgeMemoryWrapper<'a> {
+	fn read(&self, source_addr: Pointer<u8>, size: usize) -> Result<Vec<u8>> {
+		let mut buffer = vec![0u8; size];
+		self.read_into(source_addr, &mut buffer)?;
+		Ok(buffer)
+	}
+
+	fn read_into(&self, source_addr: Pointer<u8>, destination: &mut [u8]) -> Result<()> {
+		let range = self.checked_range(source_addr, destination.len()).map_err(WasmError::Other)?;
+		let ptr = self.data_ptr().map_err(WasmError::Other)?;
+		let data = unsafe { std::slice::from_raw_parts(ptr, self.data_len()) };
+		destination.copy_from_slice(&data[range]);
+		Ok(())
+	}
+
+	fn write_from(&self, dest_addr: Pointer<u8>, source: &[u8]) -> Result<()> {
+		let range = self.checked_range(dest_addr, source.len()).map_err(WasmError::Other)?;
+		let ptr = self.data_ptr().map_err(WasmError::Other)?;
+		let data = unsafe { std::slice::from_raw_parts_mut(ptr, self.data_len()) };
+		data[range].copy_from_slice(source);
+		Ok(())
+	}
 }
 
 impl<'a> sp_wasm_interface::FunctionContext for HostContext<'a> {
@@ -91,40 +335,44 @@ impl<'a> sp_wasm_interface::FunctionContext for HostContext<'a> {
 		address: Pointer<u8>,
 		dest: &mut [u8],
 	) -> sp_wasm_interface::Result<()> {
-		util::read_memory_into(util::memory_slice(&self.memory), address, dest)
-			.map_err(|e| e.to_string())
+		WasmEdgeMemoryWrapper::new(&self.memory).read_into(address, dest).map_err(|e| e.to_string())
 	}
 
 	fn write_memory(&mut self, address: Pointer<u8>, data: &[u8]) -> sp_wasm_interface::Result<()> {
-		util::write_memory_from(util::memory_slice_mut(&mut self.memory), address, data)
-			.map_err(|e| e.to_string())
+		WasmEdgeMemoryWrapper::new(&self.memory).write_from(address, data).map_err(|e| e.to_string())
 	}
 
 	fn allocate_memory(&mut self, size: WordSize) -> sp_wasm_interface::Result<Pointer<u8>> {
-		let memory_slice = unsafe {
-			std::slice::from_raw_parts_mut(
-				self.memory
-					.data_pointer_mut(0, 1)
-					.expect("failed to returns the mut data pointer to the Memory."),
-				(self.memory.size() * 64 * 1024) as usize,
-			)
-		};
+		self.host_state.check_heap_ceiling(size)?;
 
-		self.host_state
+		let memory_slice = unsafe { WasmEdgeMemoryWrapper::new(&self.memory).full_slice_mut() };
+
+		let ptr = self
+			.host_state
 			.allocator()
 			.allocate(memory_slice, size)
-			.map_err(|e| e.to_string())
+			.map_err(|e| e.to_string())?;
+
+		// The allocator rounds `size` up and adds a header, so re-check what it really took.
+		if let Some(max_heap_size) = self.host_state.max_heap_size {
+			let allocated = self.host_state.allocation_stats().bytes_allocated;
+			if allocated > max_heap_size {
+				self.host_state
+					.allocator()
+					.deallocate(memory_slice, ptr)
+					.map_err(|e| e.to_string())?;
+				return Err(format!(
+					"allocation of {} bytes would exceed the configured heap ceiling of {} bytes",
+					size, max_heap_size
+				))
+			}
+		}
+
+		Ok(ptr)
 	}
 
 	fn deallocate_memory(&mut self, ptr: Pointer<u8>) -> sp_wasm_interface::Result<()> {
-		let memory_slice = unsafe {
-			std::slice::from_raw_parts_mut(
-				self.memory
-					.data_pointer_mut(0, 1)
-					.expect("failed to returns the mut data pointer to the Memory."),
-				(self.memory.size() * 64 * 1024) as usize,
-			)
-		};
+		let memory_slice = unsafe { WasmEdgeMemoryWrapper::new(&self.memory).full_slice_mut() };
 
 		self.host_state
 			.allocator()
@@ -158,9 +406,7 @@ impl<'a> Sandbox for HostContext<'a> {
 			Ok(buffer) => buffer,
 		};
 
-		if util::write_memory_from(util::memory_slice_mut(&mut self.memory), buf_ptr, &buffer)
-			.is_err()
-		{
+		if WasmEdgeMemoryWrapper::new(&self.memory).write_from(buf_ptr, &buffer).is_err() {
 			return Ok(sandbox_env::ERR_OUT_OF_BOUNDS)
 		}
 
@@ -178,7 +424,7 @@ impl<'a> Sandbox for HostContext<'a> {
 
 		let len = val_len as usize;
 
-		let buffer = match util::read_memory(util::memory_slice(&self.memory), val_ptr, len) {
+		let buffer = match WasmEdgeMemoryWrapper::new(&self.memory).read(val_ptr, len) {
 			Err(_) => return Ok(sandbox_env::ERR_OUT_OF_BOUNDS),
 			Ok(buffer) => buffer,
 		};
@@ -335,12 +581,8 @@ impl<'a, 'b> sandbox::SandboxContext for SandboxContext<'a, 'b> {
 		state: u32,
 		func_idx: SupervisorFuncIndex,
 	) -> Result<i64> {
-		let mut executor = Executor::new(None, None).map_err(|e| {
-			WasmError::Other(format!("fail to create a WasmEdge Executor context: {}", e))
-		})?;
-
 		let result = self.dispatch_thunk.call(
-			&mut executor,
+			self.host_context.host_state.executor_mut(),
 			vec![
 				WasmValue::from_i32(u32::from(invoke_args_ptr) as i32),
 				WasmValue::from_i32(invoke_args_len as i32),